@@ -1,30 +1,246 @@
 use core::str;
 use futures_util::StreamExt;
 use std::{
-    env, fs,
-    io::{self, Write},
+    collections::BTreeMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, IsTerminal, Write},
     path::{self, Path},
     process,
+    time::Duration,
 };
 use tempdir::TempDir;
 
 use bytes::Bytes;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
 
 const SIGNUP_PROMPT: &'static str = "This app requires an OpenAI API key.\nYou can sign up for an OpenAI account for free and get yours using the below link";
 const SIGNUP_LINK: &'static str = "https://platform.openai.com/account/api-keys";
 const ENTER_API_KEY_PROMPT: &'static str = "Please enter your OpenAI API Key:";
 
-const OPENAI_COMPLETION_ENDPOINT: &'static str = "https://api.openai.com/v1/chat/completions";
+const DEFAULT_API_BASE: &'static str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &'static str = "gpt-3.5-turbo";
+const DEFAULT_MAX_TOKENS: usize = 4096;
+
+/// Fixed per-message token overhead for role/formatting, matching OpenAI's
+/// rough accounting.
+const PER_MESSAGE_TOKEN_OVERHEAD: usize = 4;
 
 const TOKEN_VARIABLE: &'static str = "OPENAI_API_TOKEN";
+const API_BASE_VARIABLE: &'static str = "OPENAI_API_BASE";
+const MODEL_VARIABLE: &'static str = "OPENAI_MODEL";
+const PROXY_VARIABLE: &'static str = "OPENAI_PROXY";
+
+/// Number of times a throttled or failed request is retried before giving up.
+const MAX_RETRIES: u32 = 3;
 const APP_FOLDER: &'static str = ".gpterm";
 const TOKEN_FILE: &'static str = "token";
+const DEFAULT_PROFILE: &'static str = "default";
+const CONFIG_FILE: &'static str = "config.toml";
+const ROLES_TOML_FILE: &'static str = "roles.toml";
+const ROLES_YAML_FILE: &'static str = "roles.yaml";
+const SESSIONS_FOLDER: &'static str = "sessions";
+
+/// A reusable system persona ("shell expert", "concise", ...) loaded from the
+/// roles file and prepended to the conversation as a `System` message.
+#[derive(Deserialize, Clone)]
+struct Role {
+    name: String,
+    content: String,
+}
+
+/// On-disk shape of `~/.gpterm/roles.{toml,yaml}` — a flat list of roles, as in
+/// aichat's `roles.yaml`.
+#[derive(Deserialize, Default)]
+struct RolesFile {
+    #[serde(default)]
+    roles: Vec<Role>,
+}
+
+impl RolesFile {
+    /// Load roles from `roles.toml`, falling back to `roles.yaml`. A missing or
+    /// malformed file yields an empty store rather than a hard error.
+    fn load(local_app_folder: &Path) -> RolesFile {
+        let toml_path = Path::join(local_app_folder, ROLES_TOML_FILE);
+        if let Ok(contents) = fs::read_to_string(&toml_path) {
+            return toml::from_str(&contents).unwrap_or_default();
+        }
+
+        let yaml_path = Path::join(local_app_folder, ROLES_YAML_FILE);
+        if let Ok(contents) = fs::read_to_string(&yaml_path) {
+            return serde_yaml::from_str(&contents).unwrap_or_default();
+        }
+
+        RolesFile::default()
+    }
+
+    /// Look up a role by name.
+    fn get(&self, name: &str) -> Option<&Role> {
+        self.roles.iter().find(|role| role.name == name)
+    }
+}
+
+/// User configuration, resolved from (lowest to highest precedence) built-in
+/// defaults, the `config.toml` file, environment variables, then CLI flags.
+struct Config {
+    /// Base URL of an OpenAI-compatible server, without the trailing path.
+    api_base: String,
+    /// Model name passed through in the completion request body.
+    model: String,
+    /// Token budget the conversation is trimmed to before each request.
+    max_tokens: usize,
+    /// Whether to ANSI-highlight streamed Markdown output.
+    highlight: bool,
+    /// Optional HTTP(S) proxy URL for outbound requests.
+    proxy: Option<String>,
+}
+
+/// On-disk shape of `~/.gpterm/config.toml`. Everything is optional so a
+/// partial file only overrides the keys it sets.
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    api_base: Option<String>,
+    model: Option<String>,
+    max_tokens: Option<usize>,
+    highlight: Option<bool>,
+    proxy: Option<String>,
+}
+
+impl Config {
+    /// Resolve configuration by layering the config file, environment and the
+    /// parsed CLI flags on top of the defaults.
+    fn resolve(local_app_folder: &Path, args: &Args) -> Config {
+        let file = read_config_file(local_app_folder);
+
+        let api_base = args
+            .api_base
+            .clone()
+            .or_else(|| env::var(API_BASE_VARIABLE).ok())
+            .or(file.api_base)
+            .unwrap_or_else(|| DEFAULT_API_BASE.to_string());
+
+        let model = args
+            .model
+            .clone()
+            .or_else(|| env::var(MODEL_VARIABLE).ok())
+            .or(file.model)
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+
+        let max_tokens = file.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+
+        let highlight = if args.no_highlight {
+            false
+        } else {
+            file.highlight.unwrap_or(true)
+        };
+
+        let proxy = env::var(PROXY_VARIABLE).ok().or(file.proxy);
 
-#[derive(Serialize, Deserialize, Debug)]
+        Config {
+            api_base,
+            model,
+            max_tokens,
+            highlight,
+            proxy,
+        }
+    }
+
+    /// Build the chat-completions URL from the configured base.
+    fn completion_endpoint(&self) -> String {
+        format!("{}/chat/completions", self.api_base.trim_end_matches('/'))
+    }
+}
+
+#[test]
+fn completion_endpoint_built_from_base() {
+    let config = Config {
+        api_base: String::from("https://api.openai.com/v1"),
+        model: String::from(DEFAULT_MODEL),
+        max_tokens: DEFAULT_MAX_TOKENS,
+        highlight: false,
+        proxy: None,
+    };
+    assert_eq!(
+        config.completion_endpoint(),
+        "https://api.openai.com/v1/chat/completions"
+    );
+}
+
+#[test]
+fn completion_endpoint_trims_trailing_slash() {
+    let config = Config {
+        api_base: String::from("http://localhost:8080/v1/"),
+        model: String::from(DEFAULT_MODEL),
+        max_tokens: DEFAULT_MAX_TOKENS,
+        highlight: false,
+        proxy: None,
+    };
+    assert_eq!(
+        config.completion_endpoint(),
+        "http://localhost:8080/v1/chat/completions"
+    );
+}
+
+fn read_config_file(local_app_folder: &Path) -> ConfigFile {
+    let path_to_config = Path::join(local_app_folder, CONFIG_FILE);
+    match fs::read_to_string(path_to_config) {
+        Ok(contents) => toml::from_str(&contents).unwrap_or_default(),
+        Err(_) => ConfigFile::default(),
+    }
+}
+
+/// Flags parsed off the command line. Kept deliberately small and hand-rolled
+/// to match the rest of the REPL's plain-`std` style.
+#[derive(Default)]
+struct Args {
+    api_base: Option<String>,
+    model: Option<String>,
+    role: Option<String>,
+    session: Option<String>,
+    profile: Option<String>,
+    no_highlight: bool,
+}
+
+fn parse_args() -> Args {
+    let mut args = Args::default();
+    let mut argv = env::args().skip(1);
+
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--model" => args.model = argv.next(),
+            "--api-base" => args.api_base = argv.next(),
+            "--role" => args.role = argv.next(),
+            "--session" => args.session = argv.next(),
+            "--profile" => args.profile = argv.next(),
+            "--no-highlight" => args.no_highlight = true,
+            other => {
+                if let Some(value) = other.strip_prefix("--model=") {
+                    args.model = Some(value.to_string());
+                } else if let Some(value) = other.strip_prefix("--api-base=") {
+                    args.api_base = Some(value.to_string());
+                } else if let Some(value) = other.strip_prefix("--role=") {
+                    args.role = Some(value.to_string());
+                } else if let Some(value) = other.strip_prefix("--session=") {
+                    args.session = Some(value.to_string());
+                } else if let Some(value) = other.strip_prefix("--profile=") {
+                    args.profile = Some(value.to_string());
+                }
+            }
+        }
+    }
+
+    args
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "lowercase")]
 enum MessageRole {
+    System,
     User,
     Assistant,
 }
@@ -52,8 +268,13 @@ async fn main() {
         }
     };
 
+    let args = parse_args();
+    let config = Config::resolve(&local_app_folder, &args);
+
+    let profile = args.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+
     // Retrieve previously saved users API token or ask them to input it
-    let api_key = match get_openai_api_key(&local_app_folder) {
+    let api_key = match get_openai_api_key(&local_app_folder, profile) {
         Some(key) => key,
         None => {
             print!(
@@ -67,7 +288,7 @@ async fn main() {
                 process::exit(exitcode::USAGE);
             });
 
-            if let Err(e) = save_openai_api_key(&local_app_folder, &key) {
+            if let Err(e) = save_openai_api_key(&local_app_folder, profile, &key) {
                 eprintln!("Failed to save api key to disk {}", e);
             };
 
@@ -75,8 +296,27 @@ async fn main() {
         }
     };
 
+    let roles = RolesFile::load(&local_app_folder);
     let mut conversation: Vec<Message> = Vec::new();
 
+    // Apply a starting role (if requested) as the head system message.
+    if let Some(role_name) = &args.role {
+        match roles.get(role_name) {
+            Some(role) => set_system_message(&mut conversation, &role.content),
+            None => eprintln!("Unknown role '{}'", role_name),
+        }
+    }
+
+    // Rehydrate a saved session so the next completion includes prior context.
+    if let Some(session_name) = &args.session {
+        match load_session(&local_app_folder, session_name) {
+            Ok(messages) => conversation.extend(messages),
+            Err(e) => eprintln!("Could not load session '{}': {}", session_name, e),
+        }
+    }
+
+    let mut highlighter = Highlighter::new(&config, io::stdout().is_terminal());
+
     println!("Type your message - when finished, type ;; and press enter");
     loop {
         let mut message = String::new();
@@ -92,14 +332,60 @@ async fn main() {
                 .expect("Read input from terminal");
 
             // Check for commands
-            match message.as_str() {
-                "exit\n" => process::exit(exitcode::OK),
+            match message.trim_end() {
+                "exit" => process::exit(exitcode::OK),
                 "reset" => {
-                    conversation.clear();
+                    // Keep the active system message so a role survives reset.
+                    conversation.retain(|m| m.role == MessageRole::System);
                     println!("Cleared previous conversation");
                     message = String::new();
                     continue;
                 }
+                command if command.starts_with("save ") => {
+                    let name = command.trim_start_matches("save ").trim();
+                    match save_session(&local_app_folder, name, &conversation) {
+                        Ok(_) => println!("Saved session '{}'", name),
+                        Err(e) => eprintln!("Could not save session '{}': {}", name, e),
+                    }
+                    message = String::new();
+                    continue;
+                }
+                command if command.starts_with("load ") => {
+                    let name = command.trim_start_matches("load ").trim();
+                    match load_session(&local_app_folder, name) {
+                        Ok(messages) => {
+                            conversation = messages;
+                            println!("Loaded session '{}'", name);
+                        }
+                        Err(e) => eprintln!("Could not load session '{}': {}", name, e),
+                    }
+                    message = String::new();
+                    continue;
+                }
+                "sessions" => {
+                    let names = list_sessions(&local_app_folder);
+                    if names.is_empty() {
+                        println!("No saved sessions");
+                    } else {
+                        for name in names {
+                            println!("{}", name);
+                        }
+                    }
+                    message = String::new();
+                    continue;
+                }
+                command if command.starts_with("role ") => {
+                    let role_name = command.trim_start_matches("role ").trim();
+                    match roles.get(role_name) {
+                        Some(role) => {
+                            set_system_message(&mut conversation, &role.content);
+                            println!("Switched to role '{}'", role_name);
+                        }
+                        None => eprintln!("Unknown role '{}'", role_name),
+                    }
+                    message = String::new();
+                    continue;
+                }
                 _ => {}
             }
         }
@@ -111,8 +397,15 @@ async fn main() {
             content: String::from(message.trim_end_matches(";;\n")),
         });
 
+        // Keep the request under the model's context window.
+        let trimmed = trim_conversation(&mut conversation, config.max_tokens);
+        if trimmed > 0 {
+            println!("[trimmed {} older message(s) to stay within the token budget]", trimmed);
+        }
+
         // Get ChatGPT response as SSE stream
-        let mut res_stream = get_completion(&conversation, &api_key).await.unwrap();
+        let mut res_stream = get_completion(&conversation, &api_key, &config).await.unwrap();
+        highlighter.reset();
         // Sometimes it will split up an individual line of JSON as two SSE events
         let mut partial_message = String::new();
 
@@ -156,15 +449,17 @@ async fn main() {
                 };
                 let content = response.content.unwrap_or(String::new());
 
-                // Since we are using print!() and not println!() we should flush
-                print!("{}", &content);
-                let _ = io::stdout().flush();
+                // Stream the delta through the Markdown highlighter
+                highlighter.push(&content);
 
                 message.content += &content;
                 conversation.push(message);
             }
         }
 
+        // Flush any buffered highlighter state at the end of the turn
+        highlighter.finish();
+
         // Spacing between messages to make conversation easier to read
         print!("\n\n");
     }
@@ -176,7 +471,7 @@ fn get_some_openai_api_key_from_env_var() {
 
     env::set_var(TOKEN_VARIABLE, &api_key_env_var);
     assert_eq!(
-        get_openai_api_key(&path::PathBuf::new()),
+        get_openai_api_key(&path::PathBuf::new(), DEFAULT_PROFILE),
         Some(api_key_env_var)
     );
     env::remove_var(TOKEN_VARIABLE);
@@ -188,12 +483,12 @@ fn get_some_openai_api_key_prefer_env_var() {
 
     let fs_token = cuid2::create_id();
     let tmp_dir = TempDir::new(&cuid2::create_id()).expect("can create temp folder for test");
-    let tmp_token_file = Path::join(&tmp_dir.path(), TOKEN_FILE);
-    fs::write(&tmp_token_file, &fs_token).expect("can write temp token file");
+    let tmp_path = tmp_dir.into_path();
+    save_openai_api_key(&tmp_path, DEFAULT_PROFILE, &fs_token).expect("can write token store");
 
     env::set_var(TOKEN_VARIABLE, &api_key_env_var);
     assert_eq!(
-        get_openai_api_key(&tmp_dir.into_path()),
+        get_openai_api_key(&tmp_path, DEFAULT_PROFILE),
         Some(api_key_env_var)
     );
     env::remove_var(TOKEN_VARIABLE);
@@ -204,19 +499,38 @@ fn get_some_openai_api_key_from_fs() {
     let example_token = cuid2::create_id();
 
     let tmp_dir = TempDir::new(&cuid2::create_id()).expect("can create temp folder for test");
-    let tmp_token_file = Path::join(&tmp_dir.path(), TOKEN_FILE);
-    fs::write(&tmp_token_file, &example_token).expect("can write temp token file");
+    let tmp_path = tmp_dir.into_path();
+    save_openai_api_key(&tmp_path, DEFAULT_PROFILE, &example_token).expect("can write token store");
 
     assert_eq!(
-        get_openai_api_key(&tmp_dir.into_path()),
+        get_openai_api_key(&tmp_path, DEFAULT_PROFILE),
         Some(example_token)
     );
 }
 
+#[test]
+fn get_some_openai_api_key_per_profile() {
+    let work_token = cuid2::create_id();
+    let personal_token = cuid2::create_id();
+
+    let tmp_dir = TempDir::new(&cuid2::create_id()).expect("can create temp folder for test");
+    let tmp_path = tmp_dir.into_path();
+
+    save_openai_api_key(&tmp_path, "work", &work_token).expect("can write token store");
+    save_openai_api_key(&tmp_path, "personal", &personal_token).expect("can write token store");
+
+    assert_eq!(get_openai_api_key(&tmp_path, "work"), Some(work_token));
+    assert_eq!(
+        get_openai_api_key(&tmp_path, "personal"),
+        Some(personal_token)
+    );
+    assert_eq!(get_openai_api_key(&tmp_path, "missing"), None);
+}
+
 #[test]
 fn get_none_openai_api_key_no_folder() {
     assert_eq!(
-        get_openai_api_key(&path::PathBuf::from("this_doesnt_exist")),
+        get_openai_api_key(&path::PathBuf::from("this_doesnt_exist"), DEFAULT_PROFILE),
         None
     );
 }
@@ -225,24 +539,25 @@ fn get_none_openai_api_key_no_folder() {
 fn get_none_openai_api_key_no_file() {
     let tmp_dir = TempDir::new(&cuid2::create_id()).expect("can create temporary folder");
 
-    assert_eq!(get_openai_api_key(&tmp_dir.into_path()), None);
+    assert_eq!(get_openai_api_key(&tmp_dir.into_path(), DEFAULT_PROFILE), None);
 }
 
-fn get_openai_api_key<'a>(local_app_folder: &path::PathBuf) -> Option<String> {
-    if let Ok(token) = env::var(TOKEN_VARIABLE) {
-        return Some(token);
-    };
-
-    let path_to_token_file = Path::join(&local_app_folder, TOKEN_FILE);
-    if !path_to_token_file.exists() {
-        return None;
-    };
+/// Load the profile-keyed token store, returning an empty store when the file
+/// is absent or unreadable.
+fn load_token_store(local_app_folder: &Path) -> BTreeMap<String, String> {
+    let path_to_token_file = Path::join(local_app_folder, TOKEN_FILE);
+    match fs::read_to_string(path_to_token_file) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+        Err(_) => BTreeMap::new(),
+    }
+}
 
-    if let Ok(token) = fs::read_to_string(path_to_token_file) {
+fn get_openai_api_key(local_app_folder: &Path, profile: &str) -> Option<String> {
+    if let Ok(token) = env::var(TOKEN_VARIABLE) {
         return Some(token);
     };
 
-    return None;
+    load_token_store(local_app_folder).remove(profile)
 }
 
 #[test]
@@ -253,11 +568,12 @@ fn save_ok_openai_api_key() {
 
     println!("{}", tmpdir_path.display());
 
-    assert!(!save_openai_api_key(&tmpdir_path, &api_key).is_err());
+    assert!(!save_openai_api_key(&tmpdir_path, DEFAULT_PROFILE, &api_key).is_err());
 
-    let written_api_key =
-        fs::read_to_string(Path::join(&tmpdir_path, TOKEN_FILE)).expect("can read token file");
-    assert_eq!(written_api_key, api_key);
+    assert_eq!(
+        get_openai_api_key(&tmpdir_path, DEFAULT_PROFILE),
+        Some(api_key)
+    );
 }
 
 #[test]
@@ -265,11 +581,12 @@ fn save_ok_openai_api_key_create_folder() {
     let api_key = cuid2::create_id();
     let tmpdir_path = path::PathBuf::from(&cuid2::create_id());
 
-    assert!(!save_openai_api_key(&tmpdir_path, &api_key).is_err());
+    assert!(!save_openai_api_key(&tmpdir_path, DEFAULT_PROFILE, &api_key).is_err());
 
-    let written_api_key =
-        fs::read_to_string(Path::join(&tmpdir_path, TOKEN_FILE)).expect("can read token file");
-    assert_eq!(written_api_key, api_key);
+    assert_eq!(
+        get_openai_api_key(&tmpdir_path, DEFAULT_PROFILE),
+        Some(api_key)
+    );
 
     fs::remove_dir_all(tmpdir_path).expect("can cleanup temp dir");
 }
@@ -278,20 +595,458 @@ fn save_ok_openai_api_key_create_folder() {
 fn save_err_openai_api_key_invalid_folder() {
     let api_key = cuid2::create_id();
 
-    assert!(save_openai_api_key(&path::PathBuf::new(), &api_key).is_err());
+    assert!(save_openai_api_key(&path::PathBuf::new(), DEFAULT_PROFILE, &api_key).is_err());
 }
 
-fn save_openai_api_key(local_app_folder: &path::PathBuf, api_key: &str) -> Result<(), io::Error> {
+#[cfg(unix)]
+#[test]
+fn save_openai_api_key_is_not_world_readable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmpdir = TempDir::new(&cuid2::create_id()).expect("can create temporary folder");
+    let tmpdir_path = tmpdir.into_path();
+    let api_key = cuid2::create_id();
+
+    save_openai_api_key(&tmpdir_path, DEFAULT_PROFILE, &api_key).expect("can write token store");
+
+    let metadata =
+        fs::metadata(Path::join(&tmpdir_path, TOKEN_FILE)).expect("can stat token file");
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+}
+
+/// Persist `api_key` under `profile` in the token store, creating the token
+/// file with owner-only (`0600`) permissions so secrets are never left
+/// world-readable.
+fn save_openai_api_key(
+    local_app_folder: &Path,
+    profile: &str,
+    api_key: &str,
+) -> Result<(), io::Error> {
     if !local_app_folder.exists() {
         fs::create_dir(&local_app_folder)?;
     };
 
+    let mut store = load_token_store(local_app_folder);
+    store.insert(profile.to_string(), api_key.to_string());
+    let serialized =
+        serde_json::to_string_pretty(&store).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
     let path_to_token_file = Path::join(&local_app_folder, TOKEN_FILE);
-    fs::write(path_to_token_file, api_key)?;
+    write_token_file(&path_to_token_file, &serialized)?;
+
+    return Ok(());
+}
+
+/// Write the token file with restricted permissions. On Unix the file is
+/// created (and, if it already existed, re-chmod'd) to `0600`; on other
+/// platforms permissions are left to the OS default.
+fn write_token_file(path: &Path, contents: &str) -> Result<(), io::Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents.as_bytes())?;
+
+    // Tighten permissions in case the file pre-dated this change.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = file.metadata()?.permissions();
+        permissions.set_mode(0o600);
+        file.set_permissions(permissions)?;
+    }
 
     return Ok(());
 }
 
+/// Cheap token estimate for a single message: roughly four characters per
+/// token plus a fixed per-message overhead for role/formatting.
+fn estimate_tokens(message: &Message) -> usize {
+    message.content.chars().count() / 4 + PER_MESSAGE_TOKEN_OVERHEAD
+}
+
+/// Drop the oldest messages until the estimated token total fits within
+/// `max_tokens`. A head `System` message and the latest user turn are always
+/// preserved. Returns how many messages were trimmed.
+fn trim_conversation(conversation: &mut Vec<Message>, max_tokens: usize) -> usize {
+    let mut trimmed = 0;
+
+    loop {
+        let total: usize = conversation.iter().map(estimate_tokens).sum();
+        if total <= max_tokens {
+            break;
+        }
+
+        let last = conversation.len().saturating_sub(1);
+        let remove_idx = conversation
+            .iter()
+            .enumerate()
+            .find(|(idx, message)| message.role != MessageRole::System && *idx != last)
+            .map(|(idx, _)| idx);
+
+        match remove_idx {
+            Some(idx) => {
+                conversation.remove(idx);
+                trimmed += 1;
+            }
+            // Nothing left to drop without violating the invariants.
+            None => break,
+        }
+    }
+
+    trimmed
+}
+
+#[test]
+fn trim_conversation_preserves_system_and_latest() {
+    let mut conversation = vec![
+        Message {
+            id: String::new(),
+            role: MessageRole::System,
+            content: String::from("x").repeat(4000),
+        },
+        Message {
+            id: String::new(),
+            role: MessageRole::User,
+            content: String::from("x").repeat(4000),
+        },
+        Message {
+            id: String::new(),
+            role: MessageRole::Assistant,
+            content: String::from("x").repeat(4000),
+        },
+        Message {
+            id: String::new(),
+            role: MessageRole::User,
+            content: String::from("latest"),
+        },
+    ];
+
+    let trimmed = trim_conversation(&mut conversation, 2048);
+
+    assert!(trimmed > 0);
+    assert_eq!(conversation.first().map(|m| &m.role), Some(&MessageRole::System));
+    assert_eq!(conversation.last().map(|m| m.content.as_str()), Some("latest"));
+}
+
+#[test]
+fn trim_conversation_noop_when_under_budget() {
+    let mut conversation = vec![Message {
+        id: String::new(),
+        role: MessageRole::User,
+        content: String::from("short"),
+    }];
+
+    assert_eq!(trim_conversation(&mut conversation, DEFAULT_MAX_TOKENS), 0);
+    assert_eq!(conversation.len(), 1);
+}
+
+/// Insert or replace the head `System` message, leaving the rest of the
+/// conversation untouched. The system message is always kept at index 0 so the
+/// model sees it first.
+fn set_system_message(conversation: &mut Vec<Message>, content: &str) {
+    conversation.retain(|m| m.role != MessageRole::System);
+    conversation.insert(
+        0,
+        Message {
+            id: cuid2::create_id(),
+            role: MessageRole::System,
+            content: String::from(content),
+        },
+    );
+}
+
+#[test]
+fn set_system_message_stays_at_head() {
+    let mut conversation = vec![Message {
+        id: cuid2::create_id(),
+        role: MessageRole::User,
+        content: String::from("hello"),
+    }];
+
+    set_system_message(&mut conversation, "be concise");
+
+    assert_eq!(conversation.len(), 2);
+    assert_eq!(conversation[0].role, MessageRole::System);
+    assert_eq!(conversation[0].content, "be concise");
+}
+
+#[test]
+fn set_system_message_replaces_previous() {
+    let mut conversation = Vec::new();
+    set_system_message(&mut conversation, "first");
+    set_system_message(&mut conversation, "second");
+
+    assert_eq!(conversation.len(), 1);
+    assert_eq!(conversation[0].content, "second");
+}
+
+/// Serialize the live conversation to `~/.gpterm/sessions/<name>.json`,
+/// creating the sessions folder on first use.
+fn save_session(
+    local_app_folder: &Path,
+    name: &str,
+    conversation: &Vec<Message>,
+) -> Result<(), io::Error> {
+    let sessions_folder = Path::join(local_app_folder, SESSIONS_FOLDER);
+    if !sessions_folder.exists() {
+        fs::create_dir_all(&sessions_folder)?;
+    };
+
+    let session_file = Path::join(&sessions_folder, format!("{}.json", name));
+    let serialized = serde_json::to_string_pretty(conversation)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(session_file, serialized)?;
+
+    return Ok(());
+}
+
+/// Rehydrate a previously saved session back into a `Vec<Message>`.
+fn load_session(local_app_folder: &Path, name: &str) -> Result<Vec<Message>, io::Error> {
+    let session_file = Path::join(local_app_folder, SESSIONS_FOLDER);
+    let session_file = Path::join(&session_file, format!("{}.json", name));
+
+    let contents = fs::read_to_string(session_file)?;
+    serde_json::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// List the names of every saved session (sans the `.json` extension).
+fn list_sessions(local_app_folder: &Path) -> Vec<String> {
+    let sessions_folder = Path::join(local_app_folder, SESSIONS_FOLDER);
+
+    let mut names: Vec<String> = match fs::read_dir(sessions_folder) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                    path.file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .map(String::from)
+                } else {
+                    None
+                }
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    names.sort();
+    names
+}
+
+#[test]
+fn save_then_load_session_roundtrip() {
+    let tmp_dir = TempDir::new(&cuid2::create_id()).expect("can create temp folder for test");
+    let tmp_path = tmp_dir.into_path();
+    let name = cuid2::create_id();
+
+    let conversation = vec![Message {
+        id: String::new(),
+        role: MessageRole::User,
+        content: String::from("remember this"),
+    }];
+
+    save_session(&tmp_path, &name, &conversation).expect("can save session");
+    let loaded = load_session(&tmp_path, &name).expect("can load session");
+
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].role, MessageRole::User);
+    assert_eq!(loaded[0].content, "remember this");
+}
+
+#[test]
+fn list_sessions_returns_saved_names() {
+    let tmp_dir = TempDir::new(&cuid2::create_id()).expect("can create temp folder for test");
+    let tmp_path = tmp_dir.into_path();
+
+    save_session(&tmp_path, "alpha", &Vec::new()).expect("can save session");
+    save_session(&tmp_path, "beta", &Vec::new()).expect("can save session");
+
+    assert_eq!(list_sessions(&tmp_path), vec!["alpha", "beta"]);
+}
+
+/// Streaming Markdown renderer. Buffers incoming content deltas and emits
+/// ANSI-styled output line by line, withholding a fenced code block until its
+/// closing fence arrives so the whole block can be highlighted with syntect.
+struct Highlighter {
+    enabled: bool,
+    syntax_set: SyntaxSet,
+    theme: Theme,
+    line_buffer: String,
+    in_code_block: bool,
+    code_lang: String,
+    code_buffer: String,
+}
+
+impl Highlighter {
+    /// Build a highlighter. Highlighting is only active when enabled via config
+    /// and stdout is a TTY, so piped output stays plain.
+    fn new(config: &Config, is_tty: bool) -> Highlighter {
+        Highlighter {
+            enabled: config.highlight && is_tty,
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            theme: ThemeSet::load_defaults().themes["base16-ocean.dark"].clone(),
+            line_buffer: String::new(),
+            in_code_block: false,
+            code_lang: String::new(),
+            code_buffer: String::new(),
+        }
+    }
+
+    /// Reset the per-message state between assistant turns.
+    fn reset(&mut self) {
+        self.line_buffer.clear();
+        self.in_code_block = false;
+        self.code_lang.clear();
+        self.code_buffer.clear();
+    }
+
+    /// Feed a streamed content delta, printing any now-complete lines.
+    fn push(&mut self, delta: &str) {
+        if !self.enabled {
+            print!("{}", delta);
+            let _ = io::stdout().flush();
+            return;
+        }
+
+        self.line_buffer.push_str(delta);
+        while let Some(newline) = self.line_buffer.find('\n') {
+            let line: String = self.line_buffer.drain(..=newline).collect();
+            self.render_line(&line);
+        }
+    }
+
+    /// Flush any buffered partial line (and an unterminated code block) once the
+    /// stream ends.
+    fn finish(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if !self.line_buffer.is_empty() {
+            let line = std::mem::take(&mut self.line_buffer);
+            self.render_line(&line);
+        }
+        // An unterminated fence: emit what we buffered so nothing is lost.
+        if self.in_code_block && !self.code_buffer.is_empty() {
+            let block = std::mem::take(&mut self.code_buffer);
+            self.print_code_block(&block);
+        }
+        self.reset();
+    }
+
+    fn render_line(&mut self, line: &str) {
+        let trimmed = line.trim_end_matches('\n');
+
+        if trimmed.trim_start().starts_with("```") {
+            if self.in_code_block {
+                // Closing fence: highlight and emit the buffered block.
+                let block = std::mem::take(&mut self.code_buffer);
+                self.print_code_block(&block);
+                self.in_code_block = false;
+                self.code_lang.clear();
+            } else {
+                // Opening fence: capture the language tag and start buffering.
+                self.code_lang = trimmed
+                    .trim_start()
+                    .trim_start_matches("```")
+                    .trim()
+                    .to_string();
+                self.in_code_block = true;
+                self.code_buffer.clear();
+            }
+            return;
+        }
+
+        if self.in_code_block {
+            self.code_buffer.push_str(line);
+        } else {
+            println!("{}", highlight_inline(trimmed));
+            let _ = io::stdout().flush();
+        }
+    }
+
+    fn print_code_block(&self, block: &str) {
+        let syntax = self
+            .syntax_set
+            .find_syntax_by_token(&self.code_lang)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        for line in LinesWithEndings::from(block) {
+            match highlighter.highlight_line(line, &self.syntax_set) {
+                Ok(ranges) => print!("{}", as_24_bit_terminal_escaped(&ranges[..], false)),
+                Err(_) => print!("{}", line),
+            }
+        }
+
+        // Reset terminal colors after the block.
+        print!("\x1b[0m");
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Apply a light ANSI styling pass to a single Markdown line: headers, bold
+/// (`**`) and inline code (`` ` ``).
+fn highlight_inline(line: &str) -> String {
+    const BOLD: &str = "\x1b[1m";
+    const CYAN: &str = "\x1b[36m";
+    const RESET: &str = "\x1b[0m";
+
+    if line.trim_start().starts_with('#') {
+        return format!("{}{}{}", BOLD, line, RESET);
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut bold = false;
+    let mut code = false;
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        if chars[idx] == '*' && chars.get(idx + 1) == Some(&'*') {
+            out.push_str(if bold { RESET } else { BOLD });
+            bold = !bold;
+            idx += 2;
+            continue;
+        }
+        if chars[idx] == '`' {
+            out.push_str(if code { RESET } else { CYAN });
+            code = !code;
+            idx += 1;
+            continue;
+        }
+        out.push(chars[idx]);
+        idx += 1;
+    }
+
+    if bold || code {
+        out.push_str(RESET);
+    }
+
+    out
+}
+
+#[test]
+fn highlight_inline_wraps_bold() {
+    assert_eq!(
+        highlight_inline("a **b** c"),
+        "a \x1b[1mb\x1b[0m c"
+    );
+}
+
+#[test]
+fn highlight_inline_plain_text_unchanged() {
+    assert_eq!(highlight_inline("just text"), "just text");
+}
+
 #[derive(Debug)]
 enum CompletionError {
     RequestSerialize,
@@ -325,6 +1080,7 @@ struct CompletionResponse {
 async fn get_completion(
     conversation: &Vec<Message>,
     api_key: &str,
+    config: &Config,
 ) -> Result<impl futures_core::Stream<Item = Result<Bytes, reqwest::Error>>, CompletionError> {
     // Request body for OpenAI completion
     #[derive(Serialize)]
@@ -335,29 +1091,81 @@ async fn get_completion(
     }
 
     let request_body = serde_json::to_string(&CompletionBody {
-        model: "gpt-3.5-turbo",
+        model: &config.model,
         messages: conversation,
         stream: true,
     })
     .map_err(|_| CompletionError::RequestSerialize)?;
 
-    // Open the streaming connection and handle any bad responses
-    let client = reqwest::Client::new();
-    let res = client
-        .post(OPENAI_COMPLETION_ENDPOINT)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .body(request_body)
-        .send()
-        .await
-        .map_err(|e| {
-            match e.status() {
-                Some(StatusCode::UNAUTHORIZED) => CompletionError::Unauthorized,
-                Some(StatusCode::TOO_MANY_REQUESTS) => CompletionError::OutOfTokens,
-                _ => CompletionError::UnknownRequest,
+    // Build the client, routing through a proxy when one is configured.
+    let mut client_builder = reqwest::Client::builder();
+    if let Some(proxy_url) = &config.proxy {
+        let proxy = reqwest::Proxy::all(proxy_url).map_err(|_| CompletionError::UnknownRequest)?;
+        client_builder = client_builder.proxy(proxy);
+    }
+    let client = client_builder
+        .build()
+        .map_err(|_| CompletionError::UnknownRequest)?;
+
+    let endpoint = config.completion_endpoint();
+
+    // Open the streaming connection, retrying transient throttling/5xx errors
+    // with exponential backoff before surfacing a terminal error.
+    let mut attempt = 0;
+    loop {
+        let response = client
+            .post(&endpoint)
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Content-Type", "application/json")
+            .body(request_body.clone())
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            // A transport error carries no HTTP status we can retry on.
+            Err(e) => {
+                return Err(match e.status() {
+                    Some(StatusCode::UNAUTHORIZED) => CompletionError::Unauthorized,
+                    Some(StatusCode::TOO_MANY_REQUESTS) => CompletionError::OutOfTokens,
+                    _ => CompletionError::UnknownRequest,
+                })
             }
-        })?
-        .bytes_stream();
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.bytes_stream());
+        }
+
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(CompletionError::Unauthorized);
+        }
+
+        let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+        if retryable && attempt < MAX_RETRIES {
+            // Honor a server-provided Retry-After, otherwise back off 1s, 2s, 4s.
+            let delay = retry_after(&response)
+                .unwrap_or_else(|| Duration::from_secs(1 << attempt));
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+            continue;
+        }
+
+        return Err(if status == StatusCode::TOO_MANY_REQUESTS {
+            CompletionError::OutOfTokens
+        } else {
+            CompletionError::UnknownRequest
+        });
+    }
+}
 
-    Ok(res)
+/// Parse a `Retry-After` header expressed in whole seconds, if present.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
 }